@@ -0,0 +1,244 @@
+use crate::{item_uuid, Format, Item, ItemBody, PageConfig, PageState};
+use maud::html;
+use serde::Serialize;
+
+// A single transient failure shouldn't push an error entry into every
+// subscriber's reader; only show it once a source has been down a while.
+const ERROR_ITEM_THRESHOLD: u32 = 2;
+
+fn visible_error(state: &PageState) -> Option<&str> {
+    if state.consecutive_failures > ERROR_ITEM_THRESHOLD {
+        state.error.as_deref()
+    } else {
+        None
+    }
+}
+
+pub trait Render {
+    fn extension(&self) -> &'static str;
+    fn content_type(&self) -> &'static str;
+    fn render(&self, slug: &str, page: &PageConfig, state: &PageState) -> Vec<u8>;
+}
+
+impl Render for Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Rss => "xml",
+            Format::Atom => "atom",
+            Format::JsonFeed => "json",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Rss => "application/rss+xml",
+            Format::Atom => "application/atom+xml",
+            Format::JsonFeed => "application/feed+json",
+        }
+    }
+
+    fn render(&self, slug: &str, page: &PageConfig, state: &PageState) -> Vec<u8> {
+        match self {
+            Format::Rss => build_rss(page, state).to_string().into_bytes(),
+            Format::Atom => build_atom(page, state).to_string().into_bytes(),
+            Format::JsonFeed => serde_json::to_vec_pretty(&build_jsonfeed(slug, page, state))
+                .expect("jsonfeed items are all plain strings"),
+        }
+    }
+}
+
+fn build_rss(page: &PageConfig, state: &PageState) -> rss::Channel {
+    let mut items: Vec<rss::Item> = vec![];
+
+    if let Some(error) = visible_error(state) {
+        items.push(
+            rss::ItemBuilder::default()
+                .title("Error".to_owned())
+                .link(page.url.clone())
+                .description(error_to_html(error))
+                .build(),
+        )
+    }
+
+    for c in &state.items {
+        items.push(
+            rss::ItemBuilder::default()
+                .title(c.title.as_ref().unwrap_or(&page.name).clone())
+                .link(c.url.as_ref().unwrap_or(&page.url).clone())
+                .description(match &c.body {
+                    ItemBody::Html(t) => t.clone(),
+                    ItemBody::Text(t) => text_to_html(t),
+                })
+                .guid(
+                    rss::GuidBuilder::default()
+                        .value(item_uuid(c).as_urn().to_string())
+                        .permalink(false)
+                        .build(),
+                )
+                .build(),
+        )
+    }
+    if state.items.is_empty() {
+        items.push(
+            rss::ItemBuilder::default()
+                .title(page.name.clone())
+                .link(page.url.clone())
+                .description("No items found!".to_string())
+                .guid(
+                    rss::GuidBuilder::default()
+                        .value(format!("empty:{}", page.name))
+                        .permalink(false)
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    rss::ChannelBuilder::default()
+        .title(page.name.clone())
+        .link(page.url.clone())
+        .items(items)
+        .build()
+}
+
+fn build_atom(page: &PageConfig, state: &PageState) -> atom_syndication::Feed {
+    use atom_syndication::{ContentBuilder, EntryBuilder, FeedBuilder, LinkBuilder, TextBuilder};
+
+    let mut entries: Vec<atom_syndication::Entry> = vec![];
+
+    if let Some(error) = visible_error(state) {
+        entries.push(
+            EntryBuilder::default()
+                .title(TextBuilder::default().value("Error".to_string()).build())
+                .id(format!("error:{}", page.name))
+                .updated(to_fixed_offset(state.last_checked))
+                .link(LinkBuilder::default().href(page.url.clone()).build())
+                .content(
+                    ContentBuilder::default()
+                        .content_type(Some("html".to_string()))
+                        .value(Some(error_to_html(error)))
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    for c in &state.items {
+        entries.push(
+            EntryBuilder::default()
+                .title(
+                    TextBuilder::default()
+                        .value(c.title.as_ref().unwrap_or(&page.name).clone())
+                        .build(),
+                )
+                .id(item_uuid(c).as_urn().to_string())
+                .updated(to_fixed_offset(state.last_modified))
+                .link(
+                    LinkBuilder::default()
+                        .href(c.url.as_ref().unwrap_or(&page.url).clone())
+                        .build(),
+                )
+                .content(
+                    ContentBuilder::default()
+                        .content_type(Some("html".to_string()))
+                        .value(Some(match &c.body {
+                            ItemBody::Html(t) => t.clone(),
+                            ItemBody::Text(t) => text_to_html(t),
+                        }))
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    FeedBuilder::default()
+        .title(TextBuilder::default().value(page.name.clone()).build())
+        .id(page.url.clone())
+        .updated(to_fixed_offset(state.last_modified))
+        .entries(entries)
+        .build()
+}
+
+fn to_fixed_offset(t: time::OffsetDateTime) -> atom_syndication::FixedDateTime {
+    let rfc3339 = t
+        .format(&time::format_description::well_known::Rfc3339)
+        .expect("OffsetDateTime always formats as rfc3339");
+    atom_syndication::FixedDateTime::parse_from_rfc3339(&rfc3339)
+        .expect("rfc3339 timestamps parse back as rfc3339")
+}
+
+#[derive(Serialize)]
+struct JsonFeedDoc {
+    version: &'static str,
+    title: String,
+    home_page_url: String,
+    feed_url: String,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_html: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_text: Option<String>,
+    date_published: String,
+}
+
+fn build_jsonfeed(slug: &str, page: &PageConfig, state: &PageState) -> JsonFeedDoc {
+    let date_published = state
+        .last_modified
+        .format(&time::format_description::well_known::Rfc3339)
+        .expect("OffsetDateTime always formats as rfc3339");
+
+    let mut items: Vec<JsonFeedItem> = state
+        .items
+        .iter()
+        .map(|c| json_feed_item(c, page, &date_published))
+        .collect();
+
+    if let Some(error) = visible_error(state) {
+        items.push(JsonFeedItem {
+            id: format!("error:{}", page.name),
+            url: page.url.clone(),
+            title: Some("Error".to_string()),
+            content_html: Some(error_to_html(error)),
+            content_text: None,
+            date_published: date_published.clone(),
+        });
+    }
+
+    JsonFeedDoc {
+        version: "https://jsonfeed.org/version/1.1",
+        title: page.name.clone(),
+        home_page_url: page.url.clone(),
+        feed_url: format!("{slug}.json"),
+        items,
+    }
+}
+
+fn json_feed_item(c: &Item, page: &PageConfig, date_published: &str) -> JsonFeedItem {
+    let (content_html, content_text) = match &c.body {
+        ItemBody::Html(t) => (Some(t.clone()), None),
+        ItemBody::Text(t) => (None, Some(t.clone())),
+    };
+    JsonFeedItem {
+        id: item_uuid(c).as_urn().to_string(),
+        url: c.url.as_ref().unwrap_or(&page.url).clone(),
+        title: c.title.clone(),
+        content_html,
+        content_text,
+        date_published: date_published.to_string(),
+    }
+}
+
+fn text_to_html(text: &str) -> String {
+    html! { pre { (text) } }.into_string()
+}
+
+fn error_to_html(error: &str) -> String {
+    html! { p { code { (error) } } }.into_string()
+}
@@ -7,16 +7,25 @@ use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 mod extract;
+mod feed;
+mod serve;
 
 #[derive(Deserialize)]
 struct Config {
     output_dir: PathBuf,
     state_file: PathBuf,
 
+    #[serde(default = "default_listen_addr")]
+    listen_addr: String,
+
     #[serde(flatten)]
     pages: HashMap<String, PageConfig>,
 }
 
+fn default_listen_addr() -> String {
+    "127.0.0.1:8080".to_string()
+}
+
 #[derive(Deserialize)]
 pub struct PageConfig {
     name: String,
@@ -31,6 +40,12 @@ pub struct PageConfig {
     #[serde(with = "humantime_serde")]
     cooldown: Duration,
 
+    #[serde(default = "default_max_items")]
+    max_items: usize,
+
+    #[serde(default = "default_formats")]
+    formats: Vec<Format>,
+
     mode: Mode,
 
     // HTML options
@@ -52,11 +67,27 @@ pub enum Mode {
     Json,
 }
 
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Rss,
+    Atom,
+    JsonFeed,
+}
+
 fn default_interval() -> Duration {
     Duration::from_secs(7200)
 }
 
-#[derive(Serialize, Deserialize)]
+fn default_max_items() -> usize {
+    20
+}
+
+fn default_formats() -> Vec<Format> {
+    vec![Format::Rss]
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct PageState {
     error: Option<String>,
 
@@ -67,18 +98,22 @@ struct PageState {
     last_checked: time::OffsetDateTime,
 
     http_etag: Option<String>,
+    http_last_modified: Option<String>,
+
+    #[serde(default)]
+    consecutive_failures: u32,
 
     items: Vec<Item>,
 }
 
-#[derive(Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Item {
     url: Option<String>,
     title: Option<String>,
     body: ItemBody,
 }
 
-#[derive(Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum ItemBody {
     Text(String),
@@ -93,6 +128,8 @@ impl Default for PageState {
             last_modified: now,
             last_checked: now,
             http_etag: None,
+            http_last_modified: None,
+            consecutive_failures: 0,
             items: vec![],
         }
     }
@@ -104,6 +141,8 @@ impl PageState {
             error: Some(error),
             last_checked: now,
             http_etag: None,
+            http_last_modified: None,
+            consecutive_failures: self.consecutive_failures + 1,
             ..self
         }
     }
@@ -112,6 +151,7 @@ impl PageState {
         Self {
             error: None,
             last_checked: now,
+            consecutive_failures: 0,
             ..self
         }
     }
@@ -120,18 +160,32 @@ impl PageState {
         self,
         now: time::OffsetDateTime,
         http_etag: Option<String>,
+        http_last_modified: Option<String>,
         items: Vec<Item>,
+        max_items: usize,
     ) -> Self {
-        if items == self.items {
-            self.not_modified(now)
-        } else {
-            Self {
-                error: None,
-                last_modified: now,
-                last_checked: now,
-                http_etag,
-                items,
-            }
+        let mut seen: std::collections::HashSet<uuid::Uuid> =
+            self.items.iter().map(item_uuid).collect();
+        let mut new_items: Vec<Item> = items
+            .into_iter()
+            .filter(|item| seen.insert(item_uuid(item)))
+            .collect();
+
+        if new_items.is_empty() {
+            return self.not_modified(now);
+        }
+
+        new_items.extend(self.items);
+        new_items.truncate(max_items);
+
+        Self {
+            error: None,
+            last_modified: now,
+            last_checked: now,
+            http_etag,
+            http_last_modified,
+            consecutive_failures: 0,
+            items: new_items,
         }
     }
 }
@@ -168,10 +222,14 @@ async fn update_pages(
         .await
 }
 
+// Caps retry delay at interval * 2^3, i.e. 8x the configured interval.
+const MAX_BACKOFF_EXPONENT: u32 = 3;
+
 fn is_time_to_fetch(page: &PageConfig, state: &PageState) -> bool {
+    let backoff = 2u32.pow(state.consecutive_failures.min(MAX_BACKOFF_EXPONENT));
     time::OffsetDateTime::now_utc() - Duration::from_secs(60)
         > std::cmp::max(
-            state.last_checked + page.interval,
+            state.last_checked + page.interval * backoff,
             state.last_modified + page.cooldown,
         )
 }
@@ -193,6 +251,9 @@ async fn fetch_page(client: &reqwest::Client, page: &PageConfig, state: PageStat
         if let Some(ref etag) = state.http_etag {
             request = request.header(reqwest::header::IF_NONE_MATCH, etag.clone());
         }
+        if let Some(ref last_modified) = state.http_last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified.clone());
+        }
     }
 
     let response = match request.send().await {
@@ -207,6 +268,7 @@ async fn fetch_page(client: &reqwest::Client, page: &PageConfig, state: PageStat
     };
 
     let etag_header = get_header(&response, reqwest::header::ETAG);
+    let last_modified_header = get_header(&response, reqwest::header::LAST_MODIFIED);
     let document = match response.text().await {
         Err(error) => return state.failure(now, format!("{:?}", error)),
         Ok(document) => document,
@@ -216,7 +278,13 @@ async fn fetch_page(client: &reqwest::Client, page: &PageConfig, state: PageStat
         Err(error) => return state.failure(now, format!("{:?}", error)),
         Ok(items) => items,
     };
-    state.update_content(now, etag_header, items)
+    state.update_content(
+        now,
+        etag_header,
+        last_modified_header,
+        items,
+        page.max_items,
+    )
 }
 
 fn get_header(response: &reqwest::Response, header: reqwest::header::HeaderName) -> Option<String> {
@@ -234,78 +302,20 @@ fn item_uuid(content: &Item) -> uuid::Uuid {
     Uuid::new_v5(&NAMESPACE, &bytes)
 }
 
-fn build_rss(page: &PageConfig, state: &PageState) -> rss::Channel {
-    let mut items: Vec<rss::Item> = vec![];
-
-    if let Some(error) = &state.error {
-        items.push(
-            rss::ItemBuilder::default()
-                .title("Error".to_owned())
-                .link(page.url.clone())
-                .description(error_to_html(error))
-                .build(),
-        )
-    }
-
-    for c in &state.items {
-        items.push(
-            rss::ItemBuilder::default()
-                .title(c.title.as_ref().unwrap_or(&page.name).clone())
-                .link(c.url.as_ref().unwrap_or(&page.url).clone())
-                .description(match &c.body {
-                    ItemBody::Html(t) => t.clone(),
-                    ItemBody::Text(t) => text_to_html(t),
-                })
-                .guid(
-                    rss::GuidBuilder::default()
-                        .value(item_uuid(c).as_urn().to_string())
-                        .permalink(false)
-                        .build(),
-                )
-                .build(),
-        )
-    }
-    if state.items.is_empty() {
-        items.push(
-            rss::ItemBuilder::default()
-                .title(page.name.clone())
-                .link(page.url.clone())
-                .description("No items found!".to_string())
-                .guid(
-                    rss::GuidBuilder::default()
-                        .value(format!("empty:{}", page.name))
-                        .permalink(false)
-                        .build(),
-                )
-                .build(),
-        )
-    }
-
-    rss::ChannelBuilder::default()
-        .title(page.name.clone())
-        .link(page.url.clone())
-        .items(items)
-        .build()
-}
-
-fn text_to_html(text: &str) -> String {
-    html! { pre { (text) } }.into_string()
-}
-
-fn error_to_html(error: &str) -> String {
-    html! { p { code { (error) } } }.into_string()
-}
-
 fn build_index(config: &Config) -> String {
+    use feed::Render;
+
     html! {
         html {
             head {
                 title { "Pagefeed index" }
                 @for (slug, page_config) in &config.pages {
-                    link rel="alternative"
-                        type="application/rss+xml"
-                        title=(page_config.name)
-                        href=(format!("{slug}.xml"));
+                    @for format in &page_config.formats {
+                        link rel="alternative"
+                            type=(format.content_type())
+                            title=(page_config.name)
+                            href=(format!("{slug}.{}", format.extension()));
+                    }
                 }
             }
             body {}
@@ -333,10 +343,20 @@ fn write_unless_unmodified(path: &Path, data: &[u8]) -> Result<(), Box<dyn std::
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn persist_state(
+    state_file: &Path,
+    state: &HashMap<String, PageState>,
+) -> Result<(), Box<dyn std::error::Error>> {
     use std::io::Write;
 
+    let af = atomicwrites::AtomicFile::new(state_file, atomicwrites::AllowOverwrite);
+    let state_data = toml::to_string(state)?.into_bytes();
+    af.write(|f| f.write_all(&state_data))?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config_file = std::env::args().nth(1).expect("no config file given");
     let base_path = Path::new(&config_file).parent().unwrap();
     let config: Config = toml::from_str(&std::fs::read_to_string(&config_file)?)?;
@@ -347,21 +367,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .and_then(|s| toml::from_str(&s).ok())
         .unwrap_or_default();
 
+    if std::env::args().nth(2).as_deref() == Some("serve") {
+        return serve::run(config, state_file, state).await;
+    }
+
     let state = update_pages(&config.pages, state).await;
 
-    let af = atomicwrites::AtomicFile::new(&state_file, atomicwrites::AllowOverwrite);
-    let state_data = toml::to_string(&state)?.into_bytes();
-    af.write(|f| f.write_all(&state_data))?;
-    drop(af);
+    persist_state(&state_file, &state)?;
 
     let output_dir = base_path.join(&config.output_dir);
     for (slug, page_config) in &config.pages {
         let page_state = state.get(slug).unwrap();
-        let rss = build_rss(page_config, page_state);
-        write_unless_unmodified(
-            &output_dir.join(format!("{slug}.xml")),
-            rss.to_string().as_bytes(),
-        )?;
+        for format in &page_config.formats {
+            use feed::Render;
+            write_unless_unmodified(
+                &output_dir.join(format!("{slug}.{}", format.extension())),
+                &format.render(slug, page_config, page_state),
+            )?;
+        }
     }
     write_unless_unmodified(
         &output_dir.join("index.html"),
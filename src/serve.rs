@@ -0,0 +1,143 @@
+use crate::feed::Render;
+use crate::{build_index, persist_state, update_pages, Config, Format, PageConfig, PageState};
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Config>,
+    pages: Arc<RwLock<HashMap<String, PageState>>>,
+}
+
+pub async fn run(
+    config: Config,
+    state_file: PathBuf,
+    state: HashMap<String, PageState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Arc::new(config);
+    let pages = Arc::new(RwLock::new(state));
+    let listen_addr = config.listen_addr.clone();
+
+    tokio::spawn(poll_loop(config.clone(), state_file, pages.clone()));
+
+    let app_state = AppState { config, pages };
+    let app = Router::new()
+        .route("/", get(index_handler))
+        .route("/{file}", get(feed_handler))
+        .with_state(app_state);
+
+    let listener = tokio::net::TcpListener::bind(&listen_addr).await?;
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn poll_loop(
+    config: Arc<Config>,
+    state_file: PathBuf,
+    pages: Arc<RwLock<HashMap<String, PageState>>>,
+) {
+    loop {
+        let current = pages.read().await.clone();
+        let updated = update_pages(&config.pages, current).await;
+        if let Err(error) = persist_state(&state_file, &updated) {
+            eprintln!("failed to persist state: {error}");
+        }
+        *pages.write().await = updated;
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+async fn index_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/html")],
+        build_index(&state.config),
+    )
+}
+
+async fn feed_handler(
+    State(state): State<AppState>,
+    UrlPath(file): UrlPath<String>,
+    headers: HeaderMap,
+) -> Response {
+    let Some((slug, page, format)) = lookup_feed(&state.config, &file) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let pages = state.pages.read().await;
+    let Some(page_state) = pages.get(&slug) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let body = format.render(&slug, page, page_state);
+    let etag = format!("\"{:x}\"", hash_bytes(&body));
+
+    if is_not_modified(&headers, &etag, page_state.last_modified) {
+        return StatusCode::NOT_MODIFIED.into_response();
+    }
+
+    let max_age = page.interval.as_secs();
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::ETAG, etag),
+            (
+                header::LAST_MODIFIED,
+                httpdate::fmt_http_date(to_system_time(page_state.last_modified)),
+            ),
+            (
+                header::CACHE_CONTROL,
+                format!("max-age={max_age}"),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn lookup_feed<'c>(config: &'c Config, file: &str) -> Option<(String, &'c PageConfig, Format)> {
+    for (slug, page) in &config.pages {
+        for format in &page.formats {
+            if *file == format!("{slug}.{}", format.extension()) {
+                return Some((slug.clone(), page, *format));
+            }
+        }
+    }
+    None
+}
+
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: time::OffsetDateTime) -> bool {
+    if let Some(if_none_match) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    {
+        return if_none_match == etag;
+    }
+    if let Some(if_modified_since) = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+    {
+        if let Ok(since) = httpdate::parse_http_date(if_modified_since) {
+            return since >= to_system_time(last_modified);
+        }
+    }
+    false
+}
+
+fn to_system_time(t: time::OffsetDateTime) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(t.unix_timestamp().max(0) as u64)
+}
+
+fn hash_bytes(data: &[u8]) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}